@@ -1,13 +1,15 @@
+use chrono::format::{Item, StrftimeItems};
 use chrono::prelude::*;
 use chrono::Months;
 use clap::{App, Arg};
 use std::env;
 use std::fs::OpenOptions;
-use std::io::{Error, Read, Write};
+use std::io::{Error, ErrorKind, Write};
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
-fn main() -> Result<(), Error> {
-    let matches = App::new("ponder")
+fn build_cli() -> App<'static> {
+    App::new("ponder")
         .arg(
             Arg::with_name("retro")
                 .short('r')
@@ -20,11 +22,63 @@ fn main() -> Result<(), Error> {
                 .long("reminisce")
                 .help("Opens entries from significant past intervals"),
         )
-        .get_matches();
+        .arg(
+            Arg::with_name("journal-dir")
+                .long("journal-dir")
+                .value_name("DIR")
+                .takes_value(true)
+                .help("Uses DIR instead of ~/Documents/rubberducks for journal entries"),
+        )
+        .arg(
+            Arg::with_name("no-header")
+                .long("no-header")
+                .conflicts_with_all(&["reminisce", "retro", "date"])
+                .help("Opens today's entry without appending a timestamp header"),
+        )
+        .arg(
+            Arg::with_name("print-path")
+                .long("print-path")
+                .help("Prints the entry file paths instead of opening the editor"),
+        )
+        .arg(
+            Arg::with_name("duplicate-from")
+                .long("duplicate-from")
+                .value_name("DATE")
+                .takes_value(true)
+                .allow_hyphen_values(true)
+                .conflicts_with_all(&["reminisce", "retro", "date"])
+                .help("Starts today's empty entry with the contents of the entry for DATE"),
+        )
+        .arg(
+            Arg::with_name("date")
+                .short('d')
+                .long("date")
+                .value_name("DATE")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .allow_hyphen_values(true)
+                .conflicts_with_all(&["reminisce", "retro"])
+                .help(
+                    "Opens the entry for a specific date (YYYY-MM-DD, YYYYMMDD, today, \
+                     yesterday or today-N); repeatable",
+                ),
+        )
+}
 
-    let editor = std::env::var("EDITOR").unwrap_or_else(|_| "vim".to_string());
+fn main() -> Result<(), Error> {
+    let matches = build_cli().get_matches();
+
+    let editor = resolve_editor();
+    let editor_args = match env::var("PONDER_EDITOR_ARGS") {
+        Ok(value) => split_editor_args(&value),
+        Err(_) => Vec::new(),
+    };
+    let journal_dir = match matches.value_of("journal-dir") {
+        Some(dir) => resolve_journal_dir(dir)?,
+        None => format!("{}/Documents/rubberducks", env::var("HOME").unwrap()),
+    };
 
-    if matches.is_present("reminisce") {
+    let filenames = if matches.is_present("reminisce") {
         let mut filenames = Vec::new();
         let now = Local::now();
         let today = now.naive_local().date();
@@ -55,7 +109,7 @@ fn main() -> Result<(), Error> {
 
         // Collect filenames for existing entries
         for date in dates {
-            let filename = generate_filename_for_naivedate(date);
+            let filename = generate_filename_for_naivedate(&journal_dir, date);
             if std::fs::metadata(&filename).is_ok() {
                 filenames.push(filename);
             }
@@ -63,90 +117,749 @@ fn main() -> Result<(), Error> {
 
         filenames.reverse();
 
-        if !filenames.is_empty() {
-            Command::new(&editor)
-                .args(&filenames)
-                .status()
-                .expect("Failed to open files");
-        } else {
+        if filenames.is_empty() {
             eprintln!("No entries found for reminisce intervals");
         }
+        filenames
     } else if matches.is_present("retro") {
         // Retrieve entries from the past week and open each
         let mut filenames = Vec::new();
         for i in (1..=7).rev() {
             let date = Local::now() - chrono::Duration::days(i);
-            let filename = generate_filename_for_date(date);
+            let filename = generate_filename_for_date(&journal_dir, date);
+            if std::fs::metadata(&filename).is_ok() {
+                filenames.push(filename);
+            }
+        }
+        filenames
+    } else if let Some(values) = matches.values_of("date") {
+        let dates = resolve_dates(values, Local::now().naive_local().date())?;
+
+        // Collect filenames for existing entries
+        let mut filenames = Vec::new();
+        for date in dates {
+            let filename = generate_filename_for_naivedate(&journal_dir, date);
             if std::fs::metadata(&filename).is_ok() {
                 filenames.push(filename);
             }
         }
-        if !filenames.is_empty() {
-            Command::new(&editor)
-                .args(&filenames)
-                .status()
-                .expect("Failed to open files");
+
+        if filenames.is_empty() {
+            eprintln!("No entries found for the given dates");
         }
+        filenames
     } else {
-        let filename = generate_filename_for_date(Local::now());
-        let mut file = create_or_open_file(&filename).unwrap();
-        append_date_time(&mut file).unwrap();
-        Command::new(editor)
-            .arg(filename)
-            .status()
-            .expect("Failed to open file");
+        // Header settings only matter for today's entry, so they are read and
+        // validated here rather than for every mode
+        let date_format =
+            env::var("PONDER_HEADER_DATE_FORMAT").unwrap_or_else(|_| "%B %d, %Y: %A".to_string());
+        let time_format =
+            env::var("PONDER_HEADER_TIME_FORMAT").unwrap_or_else(|_| "%H:%M:%S".to_string());
+        validate_format("PONDER_HEADER_DATE_FORMAT", &date_format)?;
+        validate_format("PONDER_HEADER_TIME_FORMAT", &time_format)?;
+        let auto_header = match env::var("PONDER_AUTO_HEADER") {
+            Ok(value) => parse_bool("PONDER_AUTO_HEADER", &value)?,
+            Err(_) => true,
+        };
+
+        let now = Local::now();
+        let filename = generate_filename_for_date(&journal_dir, now);
+        let template_source = match matches.value_of("duplicate-from") {
+            Some(value) => Some(generate_filename_for_naivedate(
+                &journal_dir,
+                parse_date(value, now.naive_local().date())?,
+            )),
+            None => None,
+        };
+        // Printing the path only initializes the entry, it doesn't start a session
+        let header = HeaderFormats {
+            date: &date_format,
+            time: if matches.is_present("print-path") {
+                None
+            } else {
+                Some(&time_format)
+            },
+        };
+        let header = if auto_header && !matches.is_present("no-header") {
+            Some(&header)
+        } else {
+            None
+        };
+
+        prepare_entry(&filename, header, template_source.as_deref(), now)?;
+        vec![filename]
+    };
+
+    if matches.is_present("print-path") {
+        for filename in &filenames {
+            println!("{}", filename);
+        }
+    } else if !filenames.is_empty() {
+        launch_editor(&editor, &editor_args, &filenames);
     }
 
     Ok(())
 }
 
-fn generate_filename_for_date(date: DateTime<Local>) -> String {
-    format!(
-        "{}/Documents/rubberducks/{}.md",
-        env::var("HOME").unwrap(),
-        date.format("%Y%m%d")
-    )
+// Canonicalize so relative paths, trailing slashes and symlinks all resolve to
+// the same absolute directory regardless of where ponder is run from
+fn resolve_journal_dir(dir: &str) -> Result<String, Error> {
+    let path = std::fs::canonicalize(dir).map_err(|e| {
+        Error::new(
+            e.kind(),
+            format!("Invalid journal directory {}: {}", dir, e),
+        )
+    })?;
+    if !path.is_dir() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Journal directory is not a directory: {}", dir),
+        ));
+    }
+    path.into_os_string().into_string().map_err(|_| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            format!("Journal directory is not valid UTF-8: {}", dir),
+        )
+    })
 }
 
-fn generate_filename_for_naivedate(date: NaiveDate) -> String {
+fn resolve_dates<'a>(
+    values: impl Iterator<Item = &'a str>,
+    today: NaiveDate,
+) -> Result<Vec<NaiveDate>, Error> {
+    let mut dates = Vec::new();
+    for value in values {
+        let date = parse_date(value, today)?;
+        if !dates.contains(&date) {
+            dates.push(date);
+        }
+    }
+    Ok(dates)
+}
+
+fn generate_filename_for_date(journal_dir: &str, date: DateTime<Local>) -> String {
+    format!("{}/{}.md", journal_dir, date.format("%Y%m%d"))
+}
+
+fn generate_filename_for_naivedate(journal_dir: &str, date: NaiveDate) -> String {
     format!(
-        "{}/Documents/rubberducks/{:04}{:02}{:02}.md",
-        env::var("HOME").unwrap(),
+        "{}/{:04}{:02}{:02}.md",
+        journal_dir,
         date.year(),
         date.month(),
         date.day()
     )
 }
 
-fn create_or_open_file(filename: &String) -> Result<std::fs::File, Error> {
-    let file = OpenOptions::new()
-        .read(true)
-        .write(true)
+fn parse_date(value: &str, today: NaiveDate) -> Result<NaiveDate, Error> {
+    let invalid_date = || {
+        Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "Invalid date (expected YYYY-MM-DD, YYYYMMDD, today, yesterday or today-N): {}",
+                value
+            ),
+        )
+    };
+
+    // Relative dates count back from today
+    let days_ago = match value {
+        "today" => Some("0"),
+        "yesterday" => Some("1"),
+        _ => value
+            .strip_prefix("today-")
+            .or_else(|| value.strip_prefix('-')),
+    };
+    if let Some(days_ago) = days_ago {
+        // u32 parsing alone would also accept a leading '+'
+        if days_ago.is_empty() || !days_ago.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(invalid_date());
+        }
+        return days_ago
+            .parse::<u32>()
+            .ok()
+            .and_then(|days| today.checked_sub_signed(chrono::Duration::days(days.into())))
+            .ok_or_else(invalid_date);
+    }
+
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .or_else(|_| NaiveDate::parse_from_str(value, "%Y%m%d"))
+        .map_err(|_| invalid_date())
+}
+
+// $VISUAL takes precedence over $EDITOR, and empty values count as unset
+fn resolve_editor() -> String {
+    ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|editor| !editor.is_empty())
+        .unwrap_or_else(|| "vim".to_string())
+}
+
+// Arguments are comma separated and each one is passed to the editor as-is,
+// without any shell interpretation
+fn split_editor_args(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .filter(|arg| !arg.is_empty())
+        .map(|arg| arg.to_string())
+        .collect()
+}
+
+fn launch_editor(editor: &str, editor_args: &[String], filenames: &[String]) {
+    Command::new(editor)
+        .args(editor_args)
+        .args(filenames)
+        .status()
+        .expect("Failed to open files");
+}
+
+fn parse_bool(name: &str, value: &str) -> Result<bool, Error> {
+    match value {
+        "true" | "1" => Ok(true),
+        "false" | "0" => Ok(false),
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("{} must be true or false: {}", name, value),
+        )),
+    }
+}
+
+fn create_file_if_missing(filename: &str) -> Result<(), Error> {
+    OpenOptions::new()
         .create(true)
         .append(true)
         .open(filename)?;
-    Ok(file)
+    Ok(())
+}
+
+struct HeaderFormats<'a> {
+    date: &'a str,
+    // None skips the per-session timestamp
+    time: Option<&'a str>,
 }
 
-fn append_date_time(file: &mut std::fs::File) -> Result<(), Error> {
-    let now = chrono::Local::now();
-    // If file is empty, append today's date in YYYY MMM, DD format as the header
-    let mut contents = String::new();
-    file.read_to_string(&mut contents)?;
+// Creates the entry if needed, appends the header and seeds it from the
+// template entry; a template may only be used to start an empty entry
+fn prepare_entry(
+    filename: &str,
+    header: Option<&HeaderFormats>,
+    template_source: Option<&str>,
+    now: DateTime<Local>,
+) -> Result<(), Error> {
+    let mut contents = read_file_or_empty(filename)?;
+    let original_len = contents.len();
+
+    let template = match template_source {
+        Some(source) => {
+            if !contents.is_empty() {
+                return Err(Error::new(
+                    ErrorKind::AlreadyExists,
+                    format!("Refusing to overwrite non-empty entry: {}", filename),
+                ));
+            }
+            std::fs::read(source).map_err(|e| {
+                Error::new(e.kind(), format!("Failed to read entry {}: {}", source, e))
+            })?
+        }
+        None => Vec::new(),
+    };
+
+    if let Some(header) = header {
+        append_date_time(&mut contents, now, header.date, header.time)?;
+    }
+    contents.extend_from_slice(&template);
+
+    if contents.len() > original_len {
+        write_file_atomically(filename, &contents)
+    } else {
+        create_file_if_missing(filename)
+    }
+}
+
+fn read_file_or_empty(filename: &str) -> Result<Vec<u8>, Error> {
+    match std::fs::read(filename) {
+        Ok(contents) => Ok(contents),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+// Write to a temporary file next to the target and rename it into place, so a
+// crash or full disk never leaves a partially written entry behind. Symlinked
+// entries are resolved first so the link survives and its target is updated
+fn write_file_atomically(filename: &str, contents: &[u8]) -> Result<(), Error> {
+    let target = resolve_symlink(filename)?;
+    let mut temp = target.clone().into_os_string();
+    temp.push(format!(".{}.tmp", std::process::id()));
+    let temp = PathBuf::from(temp);
+
+    let result = write_and_rename(&target, &temp, contents);
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    result
+}
+
+fn resolve_symlink(filename: &str) -> Result<PathBuf, Error> {
+    match std::fs::canonicalize(filename) {
+        Ok(path) => Ok(path),
+        // A dangling link still points at where the entry should be created
+        Err(e) if e.kind() == ErrorKind::NotFound => match std::fs::read_link(filename) {
+            Ok(link) => Ok(Path::new(filename)
+                .parent()
+                .unwrap_or_else(|| Path::new(""))
+                .join(link)),
+            Err(_) => Ok(PathBuf::from(filename)),
+        },
+        Err(e) => Err(e),
+    }
+}
+
+fn write_and_rename(target: &Path, temp: &Path, contents: &[u8]) -> Result<(), Error> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(temp)?;
+    if let Ok(metadata) = std::fs::metadata(target) {
+        file.set_permissions(metadata.permissions())?;
+    }
+    file.write_all(contents)?;
+    file.sync_all()?;
+    std::fs::rename(temp, target)?;
+    sync_parent_dir(target)
+}
+
+// The rename is only durable once the directory entry itself reaches disk
+#[cfg(unix)]
+fn sync_parent_dir(path: &Path) -> Result<(), Error> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    std::fs::File::open(parent)?.sync_all()
+}
+
+// Directories can't be opened for syncing on other platforms
+#[cfg(not(unix))]
+fn sync_parent_dir(_path: &Path) -> Result<(), Error> {
+    Ok(())
+}
 
-    if contents.is_empty() {
-        writeln!(file, "# {}", now.format("%B %d, %Y: %A"))?;
+fn validate_format(name: &str, format: &str) -> Result<(), Error> {
+    if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("{} is not a valid date format: {}", name, format),
+        ));
+    }
+    Ok(())
+}
+
+fn append_date_time(
+    contents: &mut Vec<u8>,
+    now: DateTime<Local>,
+    date_format: &str,
+    time_format: Option<&str>,
+) -> Result<(), Error> {
+    // If file is empty, append today's date as the header
+    let is_new = contents.is_empty();
+    if is_new {
+        writeln!(contents, "# {}", now.format(date_format))?;
         writeln!(
-            file,
+            contents,
             "\n#journal [[{} {}]] [[year {}]]",
             now.format("%B").to_string().to_lowercase(),
             now.format("%Y"),
             now.format("%Y")
         )?;
-        writeln!(file, "\n## {}\n\n", now.format("%H:%M:%S"))?;
-    } else {
-        writeln!(file, "\n\n## {}\n\n", now.format("%H:%M:%S"))?;
+    }
+
+    if let Some(time_format) = time_format {
+        let separator = if is_new { "\n" } else { "\n\n" };
+        writeln!(contents, "{}## {}\n\n", separator, now.format(time_format))?;
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("ponder-test-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn local_datetime(year: i32, month: u32, day: u32, hour: u32, min: u32) -> DateTime<Local> {
+        let naive = NaiveDate::from_ymd_opt(year, month, day)
+            .unwrap()
+            .and_hms_opt(hour, min, 0)
+            .unwrap();
+        Local.from_local_datetime(&naive).unwrap()
+    }
+
+    #[test]
+    fn resolve_dates_accepts_repeated_date_flags() {
+        let matches = build_cli()
+            .try_get_matches_from([
+                "ponder",
+                "-d",
+                "2024-01-01",
+                "--date",
+                "20240105",
+                "-d",
+                "2024-01-01",
+                "-d",
+                "yesterday",
+            ])
+            .unwrap();
+        let today = NaiveDate::from_ymd_opt(2024, 2, 10).unwrap();
+        let dates = resolve_dates(matches.values_of("date").unwrap(), today).unwrap();
+        assert_eq!(
+            dates,
+            vec![
+                NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+                NaiveDate::from_ymd_opt(2024, 1, 5).unwrap(),
+                NaiveDate::from_ymd_opt(2024, 2, 9).unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn resolve_dates_rejects_malformed_date() {
+        let today = NaiveDate::from_ymd_opt(2024, 2, 10).unwrap();
+        let err = resolve_dates(["2024-01-01", "2024-13-01"].into_iter(), today).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn date_conflicts_with_retro_and_reminisce() {
+        assert!(build_cli()
+            .try_get_matches_from(["ponder", "-d", "-7", "-r"])
+            .is_err());
+        assert!(build_cli()
+            .try_get_matches_from(["ponder", "--date", "today", "--reminisce"])
+            .is_err());
+    }
+
+    #[test]
+    fn no_header_conflicts_with_other_modes() {
+        for mode in [&["-r"][..], &["-m"], &["-d", "today"]] {
+            let args = ["ponder", "--no-header"].iter().chain(mode);
+            assert!(build_cli().try_get_matches_from(args).is_err());
+        }
+        assert!(build_cli()
+            .try_get_matches_from(["ponder", "--no-header", "--print-path"])
+            .is_ok());
+    }
+
+    #[test]
+    fn validate_format_accepts_valid_format() {
+        assert!(validate_format("PONDER_HEADER_DATE_FORMAT", "%Y-%m-%d").is_ok());
+    }
+
+    #[test]
+    fn validate_format_rejects_invalid_format() {
+        let err = validate_format("PONDER_HEADER_TIME_FORMAT", "%Q").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert!(err.to_string().contains("PONDER_HEADER_TIME_FORMAT"));
+    }
+
+    #[test]
+    fn append_date_time_uses_custom_formats_for_new_entry() {
+        let mut contents = Vec::new();
+        let now = local_datetime(2024, 1, 15, 14, 30);
+        append_date_time(&mut contents, now, "%Y-%m-%d", Some("%I:%M %p")).unwrap();
+        assert_eq!(
+            String::from_utf8(contents).unwrap(),
+            "# 2024-01-15\n\n#journal [[january 2024]] [[year 2024]]\n\n## 02:30 PM\n\n\n"
+        );
+    }
+
+    #[test]
+    fn append_date_time_only_adds_time_to_existing_entry() {
+        let mut contents = b"# 2024-01-15\n".to_vec();
+        let now = local_datetime(2024, 1, 15, 9, 5);
+        append_date_time(&mut contents, now, "%Y-%m-%d", Some("%H:%M")).unwrap();
+        assert_eq!(
+            String::from_utf8(contents).unwrap(),
+            "# 2024-01-15\n\n\n## 09:05\n\n\n"
+        );
+    }
+
+    #[test]
+    fn write_file_atomically_replaces_contents() {
+        let dir = test_dir("atomic-write");
+        let entry = dir.join("20240115.md");
+        std::fs::write(&entry, "original\n").unwrap();
+
+        write_file_atomically(entry.to_str().unwrap(), b"original\nmore\n").unwrap();
+
+        assert_eq!(std::fs::read(&entry).unwrap(), b"original\nmore\n");
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn write_file_atomically_leaves_original_intact_on_failure() {
+        let dir = test_dir("atomic-failure");
+        let entry = dir.join("20240115.md");
+        std::fs::write(&entry, "original\n").unwrap();
+        // A directory in the way of the temporary file makes the write fail
+        let temp = dir.join(format!("20240115.md.{}.tmp", std::process::id()));
+        std::fs::create_dir(&temp).unwrap();
+
+        assert!(write_file_atomically(entry.to_str().unwrap(), b"replaced\n").is_err());
+
+        assert_eq!(std::fs::read(&entry).unwrap(), b"original\n");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_file_atomically_writes_through_symlink() {
+        let dir = test_dir("atomic-symlink");
+        std::fs::create_dir(dir.join("sync")).unwrap();
+        std::fs::create_dir(dir.join("journal")).unwrap();
+        let real = dir.join("sync/real.md");
+        let link = dir.join("journal/20240115.md");
+        std::fs::write(&real, "original\n").unwrap();
+        std::os::unix::fs::symlink(&real, &link).unwrap();
+
+        write_file_atomically(link.to_str().unwrap(), b"original\nmore\n").unwrap();
+
+        assert!(std::fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(std::fs::read(&real).unwrap(), b"original\nmore\n");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_file_atomically_creates_target_of_dangling_symlink() {
+        let dir = test_dir("atomic-dangling");
+        let link = dir.join("20240115.md");
+        std::os::unix::fs::symlink("real.md", &link).unwrap();
+
+        write_file_atomically(link.to_str().unwrap(), b"new\n").unwrap();
+
+        assert!(std::fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(std::fs::read(dir.join("real.md")).unwrap(), b"new\n");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn prepare_entry_without_header_creates_empty_entry() {
+        let dir = test_dir("no-header-empty");
+        let entry = dir.join("20240115.md");
+        let now = local_datetime(2024, 1, 15, 9, 0);
+
+        prepare_entry(entry.to_str().unwrap(), None, None, now).unwrap();
+
+        assert_eq!(std::fs::read(&entry).unwrap(), b"");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn prepare_entry_without_header_leaves_existing_entry_unchanged() {
+        let dir = test_dir("no-header-existing");
+        let entry = dir.join("20240115.md");
+        std::fs::write(&entry, "# Monday\n\nthoughts\n").unwrap();
+        let now = local_datetime(2024, 1, 15, 9, 0);
+
+        prepare_entry(entry.to_str().unwrap(), None, None, now).unwrap();
+
+        assert_eq!(std::fs::read(&entry).unwrap(), b"# Monday\n\nthoughts\n");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn prepare_entry_with_header_appends_timestamp() {
+        let dir = test_dir("header-existing");
+        let entry = dir.join("20240115.md");
+        std::fs::write(&entry, "# Monday\n").unwrap();
+        let header = HeaderFormats {
+            date: "%Y-%m-%d",
+            time: Some("%H:%M"),
+        };
+        let now = local_datetime(2024, 1, 15, 9, 0);
+
+        prepare_entry(entry.to_str().unwrap(), Some(&header), None, now).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&entry).unwrap(),
+            "# Monday\n\n\n## 09:00\n\n\n"
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn resolve_journal_dir_returns_canonical_absolute_path() {
+        let dir = test_dir("journal-dir");
+        std::fs::create_dir(dir.join("journal")).unwrap();
+        let expected = std::fs::canonicalize(dir.join("journal")).unwrap();
+
+        let messy = format!("{}/journal/../journal/", dir.display());
+        assert_eq!(
+            resolve_journal_dir(&messy).unwrap(),
+            expected.to_str().unwrap()
+        );
+
+        let relative = resolve_journal_dir("src/").unwrap();
+        assert!(Path::new(&relative).is_absolute());
+        assert_eq!(
+            relative,
+            std::fs::canonicalize("src").unwrap().to_str().unwrap()
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn resolve_journal_dir_rejects_missing_directory_and_files() {
+        let dir = test_dir("journal-dir-invalid");
+        let missing = dir.join("missing");
+        let err = resolve_journal_dir(missing.to_str().unwrap()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+
+        let file = dir.join("file.md");
+        std::fs::write(&file, "").unwrap();
+        let err = resolve_journal_dir(file.to_str().unwrap()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn parse_date_resolves_relative_dates() {
+        let today = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        let days_ago = |days| today - chrono::Duration::days(days);
+        assert_eq!(parse_date("today", today).unwrap(), today);
+        assert_eq!(parse_date("yesterday", today).unwrap(), days_ago(1));
+        assert_eq!(parse_date("today-3", today).unwrap(), days_ago(3));
+        assert_eq!(parse_date("-1", today).unwrap(), days_ago(1));
+        assert_eq!(parse_date("today-0", today).unwrap(), today);
+    }
+
+    #[test]
+    fn parse_date_accepts_absolute_dates() {
+        let today = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        let expected = NaiveDate::from_ymd_opt(2023, 12, 31).unwrap();
+        assert_eq!(parse_date("2023-12-31", today).unwrap(), expected);
+        assert_eq!(parse_date("20231231", today).unwrap(), expected);
+    }
+
+    #[test]
+    fn parse_date_rejects_invalid_offsets() {
+        let today = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        for value in [
+            "today-x",
+            "today-",
+            "-",
+            "today-+1",
+            "-+1",
+            "today--1",
+            "today-1.5",
+        ] {
+            let err = parse_date(value, today).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidInput, "{}", value);
+        }
+    }
+
+    #[test]
+    fn split_editor_args_splits_on_commas_without_shell_parsing() {
+        assert_eq!(split_editor_args("--wait"), vec!["--wait"]);
+        assert_eq!(
+            split_editor_args("--wait,,--new-window,"),
+            vec!["--wait", "--new-window"]
+        );
+        assert_eq!(
+            split_editor_args("-c,set tw=72; $(rm -rf ~) | cat"),
+            vec!["-c", "set tw=72; $(rm -rf ~) | cat"]
+        );
+        assert!(split_editor_args("").is_empty());
+    }
+
+    #[test]
+    fn prepare_entry_without_time_only_initializes_entry() {
+        let dir = test_dir("header-date-only");
+        let entry = dir.join("20240115.md");
+        let header = HeaderFormats {
+            date: "%Y-%m-%d",
+            time: None,
+        };
+        let now = local_datetime(2024, 1, 15, 9, 0);
+
+        prepare_entry(entry.to_str().unwrap(), Some(&header), None, now).unwrap();
+        let initialized = std::fs::read_to_string(&entry).unwrap();
+        assert_eq!(
+            initialized,
+            "# 2024-01-15\n\n#journal [[january 2024]] [[year 2024]]\n"
+        );
+
+        prepare_entry(entry.to_str().unwrap(), Some(&header), None, now).unwrap();
+        assert_eq!(std::fs::read_to_string(&entry).unwrap(), initialized);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn prepare_entry_seeds_empty_entry_from_template() {
+        let dir = test_dir("duplicate-seed");
+        let entry = dir.join("20240115.md");
+        let source = dir.join("20240108.md");
+        std::fs::write(&source, "## Weekly review\n\n- wins:\n").unwrap();
+        let header = HeaderFormats {
+            date: "%Y-%m-%d",
+            time: Some("%H:%M"),
+        };
+        let now = local_datetime(2024, 1, 15, 9, 0);
+
+        prepare_entry(entry.to_str().unwrap(), Some(&header), source.to_str(), now).unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&entry).unwrap(),
+            "# 2024-01-15\n\n#journal [[january 2024]] [[year 2024]]\n\n## 09:00\n\n\n\
+             ## Weekly review\n\n- wins:\n"
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn prepare_entry_refuses_to_seed_non_empty_entry() {
+        let dir = test_dir("duplicate-refuse");
+        let entry = dir.join("20240115.md");
+        let source = dir.join("20240108.md");
+        std::fs::write(&entry, "already written\n").unwrap();
+        std::fs::write(&source, "template\n").unwrap();
+        let now = local_datetime(2024, 1, 15, 9, 0);
+
+        let err = prepare_entry(entry.to_str().unwrap(), None, source.to_str(), now).unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::AlreadyExists);
+        assert_eq!(std::fs::read(&entry).unwrap(), b"already written\n");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn prepare_entry_reports_missing_template() {
+        let dir = test_dir("duplicate-missing");
+        let entry = dir.join("20240115.md");
+        let source = dir.join("20240108.md");
+        let now = local_datetime(2024, 1, 15, 9, 0);
+
+        let err = prepare_entry(entry.to_str().unwrap(), None, source.to_str(), now).unwrap_err();
+
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+        assert!(!entry.exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,198 @@
+use std::path::PathBuf;
+use std::process::Command;
+
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("ponder-cli-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn ponder(journal_dir: &PathBuf) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_ponder"));
+    command
+        .arg("--journal-dir")
+        .arg(journal_dir)
+        .env("EDITOR", "true")
+        .env_remove("VISUAL")
+        .env_remove("PONDER_EDITOR_ARGS")
+        .env_remove("PONDER_AUTO_HEADER")
+        .env_remove("PONDER_HEADER_DATE_FORMAT")
+        .env_remove("PONDER_HEADER_TIME_FORMAT");
+    command
+}
+
+#[test]
+fn header_settings_are_ignored_by_read_only_modes() {
+    let dir = test_dir("header-settings");
+
+    for mode in ["--retro", "--reminisce"] {
+        let status = ponder(&dir)
+            .arg(mode)
+            .env("PONDER_AUTO_HEADER", "no")
+            .env("PONDER_HEADER_TIME_FORMAT", "%Q")
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "{} failed", mode);
+    }
+
+    let status = ponder(&dir)
+        .env("PONDER_AUTO_HEADER", "no")
+        .output()
+        .unwrap()
+        .status;
+    assert!(!status.success());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn journal_dir_override_is_used_for_todays_entry() {
+    let dir = test_dir("journal-dir");
+    std::fs::create_dir(dir.join("journal")).unwrap();
+    let entry = dir.join(format!(
+        "journal/{}.md",
+        chrono::Local::now().format("%Y%m%d")
+    ));
+
+    // A relative override resolves against the working directory
+    let status = ponder(&PathBuf::from("journal/"))
+        .current_dir(&dir)
+        .output()
+        .unwrap()
+        .status;
+    assert!(status.success());
+    assert!(entry.is_file());
+
+    let output = ponder(&dir.join("missing")).output().unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Invalid journal directory"));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn editor_args_are_forwarded_verbatim() {
+    let dir = test_dir("editor-args");
+    let marker = dir.join("marker");
+
+    let output = ponder(&dir)
+        .arg("--no-header")
+        .env("EDITOR", "echo")
+        .env(
+            "PONDER_EDITOR_ARGS",
+            format!("--wait,$(touch {}); echo hi", marker.display()),
+        )
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with(&format!("--wait $(touch {}); echo hi ", marker.display())));
+    assert!(!marker.exists());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn print_path_prints_entry_without_launching_editor() {
+    let dir = test_dir("print-path");
+    let marker = dir.join("marker");
+    let expected = std::fs::canonicalize(&dir)
+        .unwrap()
+        .join(format!("{}.md", chrono::Local::now().format("%Y%m%d")));
+
+    let mut contents = Vec::new();
+    for _ in 0..3 {
+        let output = ponder(&dir)
+            .arg("--print-path")
+            .env("EDITOR", "touch")
+            .env("PONDER_EDITOR_ARGS", &marker)
+            .output()
+            .unwrap();
+
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert_eq!(stdout, format!("{}\n", expected.display()));
+        contents.push(std::fs::read_to_string(&expected).unwrap());
+    }
+
+    assert!(!marker.exists());
+    // The entry is initialized once and repeated calls add no session headers
+    assert!(contents[0].starts_with("# "));
+    assert!(!contents[0].contains("## "));
+    assert!(contents.iter().all(|c| c == &contents[0]));
+
+    // A symlinked entry is reported at its journal location, not its target
+    #[cfg(unix)]
+    {
+        let journal = dir.join("journal");
+        std::fs::create_dir_all(dir.join("sync")).unwrap();
+        std::fs::create_dir(&journal).unwrap();
+        std::fs::write(dir.join("sync/real.md"), "synced\n").unwrap();
+        let link = std::fs::canonicalize(&journal)
+            .unwrap()
+            .join(expected.file_name().unwrap());
+        std::os::unix::fs::symlink(dir.join("sync/real.md"), &link).unwrap();
+
+        let output = ponder(&journal).arg("--print-path").output().unwrap();
+
+        assert!(output.status.success());
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert_eq!(stdout, format!("{}\n", link.display()));
+        assert!(std::fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+fn run_editor(command: &mut Command) -> String {
+    let output = command.arg("--no-header").output().unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn visual_takes_precedence_over_editor() {
+    let dir = test_dir("visual");
+
+    let stdout = run_editor(ponder(&dir).env("VISUAL", "echo").env("EDITOR", "false"));
+
+    assert!(stdout.ends_with(".md\n"));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn editor_is_used_when_visual_is_unset_or_empty() {
+    let dir = test_dir("editor");
+
+    let unset = run_editor(ponder(&dir).env("EDITOR", "echo"));
+    let empty = run_editor(ponder(&dir).env("VISUAL", "").env("EDITOR", "echo"));
+
+    assert!(unset.ends_with(".md\n"));
+    assert_eq!(unset, empty);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn vim_is_the_fallback_editor() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = test_dir("vim");
+    std::fs::create_dir(dir.join("bin")).unwrap();
+    let vim = dir.join("bin/vim");
+    std::fs::write(&vim, "#!/bin/sh\necho vim \"$@\"\n").unwrap();
+    std::fs::set_permissions(&vim, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let stdout = run_editor(
+        ponder(&dir)
+            .env_remove("EDITOR")
+            .env("VISUAL", "")
+            .env("PATH", dir.join("bin")),
+    );
+
+    assert!(stdout.starts_with("vim "));
+    std::fs::remove_dir_all(dir).unwrap();
+}